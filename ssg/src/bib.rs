@@ -0,0 +1,146 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use regex::{Captures, Regex};
+use toml::Value;
+
+/// A bibliography loaded from a Hayagriva YAML or BibTeX file, with every
+/// entry pre-formatted as its line in the reference list.
+#[derive(Debug, Clone)]
+pub(crate) struct Bibliography {
+    entries: HashMap<String, String>,
+}
+
+impl Bibliography {
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read bibliography {}", path.display()))?;
+
+        let library = if path.extension().and_then(|ext| ext.to_str()) == Some("bib") {
+            hayagriva::io::from_biblatex_str(&content).map_err(|errs| {
+                anyhow!("Failed to parse BibTeX bibliography {}: {errs:?}", path.display())
+            })?
+        } else {
+            hayagriva::io::from_yaml_str(&content)
+                .with_context(|| format!("Failed to parse Hayagriva bibliography {}", path.display()))?
+        };
+
+        let entries = library
+            .iter()
+            .map(|entry| (entry.key().to_string(), format_entry(entry)))
+            .collect();
+
+        Ok(Self { entries })
+    }
+
+    /// Expose every entry to templates as `[{citekey, reference}, ...]`.
+    pub(crate) fn to_template_value(&self) -> Value {
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort_by_key(|(citekey, _)| citekey.to_string());
+
+        Value::Array(
+            entries
+                .into_iter()
+                .map(|(citekey, reference)| {
+                    let mut table = toml::map::Map::new();
+                    table.insert("citekey".to_string(), Value::String(citekey.clone()));
+                    table.insert("reference".to_string(), Value::String(reference.clone()));
+                    Value::Table(table)
+                })
+                .collect(),
+        )
+    }
+}
+
+fn format_entry(entry: &hayagriva::Entry) -> String {
+    let authors = entry
+        .authors()
+        .map(|authors| {
+            authors
+                .iter()
+                .map(|author| author.name.clone())
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .unwrap_or_default();
+    let year = entry
+        .date()
+        .map(|date| date.year.to_string())
+        .unwrap_or_default();
+    let title = entry.title().map(|title| title.value.to_string()).unwrap_or_default();
+
+    format!("{authors} ({year}). {title}.")
+}
+
+/// Replace `[@citekey]` markers in `html` with numbered inline citations
+/// linking to an appended reference list, numbered in order of first
+/// appearance. Returns `html` unchanged if no marker matches a known entry.
+pub(crate) fn render_citations(html: &str, bibliography: &Bibliography) -> String {
+    let marker = Regex::new(r"\[@([\w:-]+)\]").expect("static regex should compile");
+
+    let mut cited = Vec::new();
+    let mut numbers = HashMap::new();
+
+    let body = marker.replace_all(html, |caps: &Captures| {
+        let citekey = &caps[1];
+        if !bibliography.entries.contains_key(citekey) {
+            return caps[0].to_string();
+        }
+
+        let number = *numbers.entry(citekey.to_string()).or_insert_with(|| {
+            cited.push(citekey.to_string());
+            cited.len()
+        });
+
+        format!(r##"<sup class="citation"><a href="#ref-{citekey}">[{number}]</a></sup>"##)
+    });
+
+    if cited.is_empty() {
+        return body.into_owned();
+    }
+
+    let mut out = body.into_owned();
+    out.push_str("\n<section class=\"bibliography\">\n<h2>References</h2>\n<ol>\n");
+    for citekey in &cited {
+        let reference = &bibliography.entries[citekey];
+        out.push_str(&format!("<li id=\"ref-{citekey}\">{reference}</li>\n"));
+    }
+    out.push_str("</ol>\n</section>\n");
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bibliography(entries: &[(&str, &str)]) -> Bibliography {
+        Bibliography {
+            entries: entries
+                .iter()
+                .map(|(key, reference)| (key.to_string(), reference.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn render_citations_numbers_in_order_of_first_appearance() {
+        let bib = bibliography(&[
+            ("doe2020", "Doe, J. (2020). A Paper."),
+            ("roe2019", "Roe, R. (2019). Another."),
+        ]);
+        let html = render_citations("See [@roe2019] and [@doe2020] and [@roe2019] again.", &bib);
+
+        assert!(html.contains(r##"href="#ref-roe2019">[1]"##));
+        assert!(html.contains(r##"href="#ref-doe2020">[2]"##));
+        assert_eq!(html.matches("[1]").count(), 2);
+        assert!(html.contains("<section class=\"bibliography\">"));
+    }
+
+    #[test]
+    fn render_citations_leaves_unknown_markers_untouched() {
+        let bib = bibliography(&[]);
+        let html = render_citations("See [@missing].", &bib);
+        assert_eq!(html, "See [@missing].");
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+const MANIFEST_FILE: &str = ".ssg-cache.toml";
+
+/// Build manifest persisted to `out_dir/.ssg-cache.toml`. Maps every output
+/// this generator produced to the combined BLAKE3 hash of the inputs that
+/// produced it, so unchanged pages can be skipped on the next build.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    #[serde(default)]
+    pages: BTreeMap<String, PageEntry>,
+    /// Per-template hash, kept for visibility into why a page was
+    /// invalidated; a page's own hash already folds its template's in.
+    #[serde(default)]
+    templates: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PageEntry {
+    /// Source path, relative to `in_dir`, so stale entries can be pruned
+    /// once their source disappears.
+    source: String,
+    hash: String,
+}
+
+impl Manifest {
+    pub(crate) fn load(out_dir: &Path) -> Self {
+        fs::read_to_string(out_dir.join(MANIFEST_FILE))
+            .ok()
+            .and_then(|content| toml::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn save(&self, out_dir: &Path) -> Result<()> {
+        let content =
+            toml::to_string_pretty(self).context("Failed to serialize the build manifest")?;
+        fs::write(out_dir.join(MANIFEST_FILE), content).context("Failed to write the build manifest")
+    }
+
+    pub(crate) fn record_template(&mut self, name: &str, hash: String) {
+        self.templates.insert(name.to_string(), hash);
+    }
+
+    /// Returns `true` when `output` needs to be (re)rendered: its hash
+    /// changed, it was never recorded, or the file on disk has gone missing.
+    pub(crate) fn is_stale(&self, output: &str, out_file: &Path, hash: &str) -> bool {
+        !out_file.is_file() || self.pages.get(output).map(|entry| entry.hash.as_str()) != Some(hash)
+    }
+
+    pub(crate) fn record_page(&mut self, output: String, source: String, hash: String) {
+        self.pages.insert(output, PageEntry { source, hash });
+    }
+
+    /// Drop entries (and, optionally, their output files) whose source no
+    /// longer exists under `in_dir`.
+    pub(crate) fn prune_missing(&mut self, in_dir: &Path, out_dir: &Path, remove_output: bool) {
+        self.pages.retain(|output, entry| {
+            let exists = in_dir.join(&entry.source).is_file();
+            if !exists && remove_output {
+                let _ = fs::remove_file(out_dir.join(output));
+            }
+            exists
+        });
+    }
+}
+
+/// Combine several byte slices into one BLAKE3 digest, hex-encoded. Each
+/// input's length is hashed ahead of its bytes so inputs can't shift across
+/// a slice boundary and alias with a different combination (e.g. `["ab",
+/// "c"]` vs `["a", "bc"]`).
+pub(crate) fn hash_inputs(inputs: &[&[u8]]) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for input in inputs {
+        hasher.update(&(input.len() as u64).to_le_bytes());
+        hasher.update(input);
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+/// Find the file backing a registered template name, the same way
+/// `register_templates` discovered it, so its bytes can be hashed.
+pub(crate) fn resolve_template_path(name: &str, templates: &[PathBuf]) -> Option<PathBuf> {
+    for template in templates {
+        if template.is_file() {
+            if template.file_stem().and_then(|s| s.to_str()) == Some(name) {
+                return Some(template.clone());
+            }
+        } else if template.is_dir() {
+            for entry in WalkDir::new(template).into_iter().filter_map(|e| e.ok()) {
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                if entry.path().extension().and_then(|e| e.to_str()) != Some("hbs") {
+                    continue;
+                }
+                let relative = match entry.path().strip_prefix(template) {
+                    Ok(relative) => relative.with_extension(""),
+                    Err(_) => continue,
+                };
+                if relative.to_str() == Some(name) {
+                    return Some(entry.path().to_path_buf());
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_inputs_is_deterministic() {
+        assert_eq!(hash_inputs(&[b"abc"]), hash_inputs(&[b"abc"]));
+    }
+
+    #[test]
+    fn hash_inputs_does_not_alias_across_slice_boundaries() {
+        assert_ne!(hash_inputs(&[b"ab", b"c"]), hash_inputs(&[b"a", b"bc"]));
+    }
+
+    #[test]
+    fn is_stale_tracks_missing_output_and_hash_changes() {
+        let dir = std::env::temp_dir().join(format!("ssg-cache-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let out_file = dir.join("page.html");
+
+        let mut manifest = Manifest::default();
+        assert!(manifest.is_stale("page.html", &out_file, "hash-a"));
+
+        fs::write(&out_file, "rendered").unwrap();
+        manifest.record_page("page.html".to_string(), "page.src".to_string(), "hash-a".to_string());
+        assert!(!manifest.is_stale("page.html", &out_file, "hash-a"));
+        assert!(manifest.is_stale("page.html", &out_file, "hash-b"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use epub_builder::{EpubBuilder, EpubContent, ReferenceType, ZipLibrary};
+use serde::Deserialize;
+
+/// A single rendered page, collected while walking `in_dir`, ready to become
+/// one chapter of the exported book.
+#[derive(Debug, Clone)]
+pub(crate) struct Chapter {
+    pub(crate) order: Option<i64>,
+    pub(crate) title: String,
+    pub(crate) url: String,
+    pub(crate) html: String,
+    pub(crate) stylesheet: Option<PathBuf>,
+    pub(crate) assets: Vec<PathBuf>,
+}
+
+/// Record (or replace, by URL) a page's chapter data for the next export.
+pub(crate) fn upsert(chapters: &mut Vec<Chapter>, chapter: Chapter) {
+    chapters.retain(|existing| existing.url != chapter.url);
+    chapters.push(chapter);
+}
+
+/// Book-level metadata, read from `in_dir/book.toml`.
+#[derive(Debug, Deserialize)]
+struct BookConfig {
+    #[serde(default = "default_title")]
+    title: String,
+    #[serde(default = "default_author")]
+    author: String,
+}
+
+impl Default for BookConfig {
+    fn default() -> Self {
+        Self {
+            title: default_title(),
+            author: default_author(),
+        }
+    }
+}
+
+fn default_title() -> String {
+    "Untitled".to_string()
+}
+
+fn default_author() -> String {
+    "Unknown".to_string()
+}
+
+fn load_config(in_dir: &Path) -> BookConfig {
+    fs::read_to_string(in_dir.join("book.toml"))
+        .ok()
+        .and_then(|content| toml::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Assemble every collected page into a single EPUB at `out_path`, ordered
+/// by each page's front-matter `order`/`nav` field (ties broken by URL) and
+/// titled/authored from `in_dir/book.toml`. Stylesheets and assets
+/// referenced by a chapter are embedded in the archive rather than linked.
+pub(crate) fn export(out_path: &Path, in_dir: &Path, out_dir: &Path, chapters: &[Chapter]) -> Result<()> {
+    let config = load_config(in_dir);
+
+    let mut sorted: Vec<&Chapter> = chapters.iter().collect();
+    sorted.sort_by_key(|chapter| (chapter.order.unwrap_or(i64::MAX), chapter.url.clone()));
+
+    let zip = ZipLibrary::new()
+        .map_err(|e| anyhow!("Failed to initialize the EPUB archive: {e}"))?;
+    let mut builder =
+        EpubBuilder::new(zip).map_err(|e| anyhow!("Failed to create the EPUB builder: {e}"))?;
+    builder
+        .metadata("title", config.title)
+        .map_err(|e| anyhow!("Failed to set the EPUB title: {e}"))?;
+    builder
+        .metadata("author", config.author)
+        .map_err(|e| anyhow!("Failed to set the EPUB author: {e}"))?;
+
+    let mut embedded = HashSet::new();
+
+    for (index, chapter) in sorted.iter().enumerate() {
+        let filename = format!("chapter_{index}.xhtml");
+        let content = EpubContent::new(filename, chapter.html.as_bytes())
+            .title(chapter.title.clone())
+            .reftype(if index == 0 {
+                ReferenceType::TitlePage
+            } else {
+                ReferenceType::Text
+            });
+        builder
+            .add_content(content)
+            .map_err(|e| anyhow!("Failed to add chapter '{}' to the EPUB: {e}", chapter.title))?;
+
+        let mut referenced = chapter.assets.clone();
+        referenced.extend(chapter.stylesheet.clone());
+
+        for asset in referenced {
+            let key = asset.to_string_lossy().into_owned();
+            if !embedded.insert(key.clone()) {
+                continue;
+            }
+            let bytes = fs::read(out_dir.join(&asset))
+                .with_context(|| format!("Failed to read {} for EPUB embedding", asset.display()))?;
+            builder
+                .add_resource(key, bytes.as_slice(), mime_for(&asset))
+                .map_err(|e| anyhow!("Failed to embed {} in the EPUB: {e}", asset.display()))?;
+        }
+    }
+
+    let mut out_file = fs::File::create(out_path)
+        .with_context(|| format!("Failed to create {}", out_path.display()))?;
+    builder
+        .generate(&mut out_file)
+        .map_err(|e| anyhow!("Failed to write the EPUB archive: {e}"))?;
+
+    Ok(())
+}
+
+fn mime_for(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("css") => "text/css",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,68 @@
+use regex::{Captures, Regex};
+use syntect::html::{ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+/// Server-side syntax highlighter for fenced code blocks.
+///
+/// Like rustdoc's source renderer, it does not inline colors: each token is
+/// wrapped in a `<span class="…">` (named after syntect's scopes) and left to
+/// a stylesheet, so highlighting works without shipping a client-side
+/// tokenizer.
+pub struct Highlighter {
+    syntax_set: SyntaxSet,
+}
+
+impl Highlighter {
+    pub fn new() -> Self {
+        Self {
+            syntax_set: SyntaxSet::load_defaults_newlines(),
+        }
+    }
+
+    /// Find `<pre><code class="language-…">` blocks in `html` and replace
+    /// their contents with classified spans.
+    pub fn highlight_code(&self, html: &str) -> String {
+        let fence = Regex::new(r#"(?s)<pre><code class="language-([\w+-]+)">(.*?)</code></pre>"#)
+            .expect("fenced code block regex should compile");
+
+        fence
+            .replace_all(html, |caps: &Captures| {
+                let lang = &caps[1];
+                let code = decode_entities(&caps[2]);
+
+                let syntax = self
+                    .syntax_set
+                    .find_syntax_by_token(lang)
+                    .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
+
+                let mut generator = ClassedHTMLGenerator::new_with_class_style(
+                    syntax,
+                    &self.syntax_set,
+                    ClassStyle::Spaced,
+                );
+                for line in LinesWithEndings::from(&code) {
+                    let _ = generator.parse_html_for_line_which_includes_newline(line);
+                }
+
+                format!(
+                    r#"<pre><code class="language-{lang} highlight">{}</code></pre>"#,
+                    generator.finalize()
+                )
+            })
+            .into_owned()
+    }
+}
+
+fn decode_entities(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Default stylesheet matching the class names emitted by [`Highlighter`].
+pub fn default_css() -> &'static str {
+    include_str!("highlight.css")
+}
@@ -4,31 +4,74 @@ use std::{
 };
 
 use anyhow::{anyhow, bail, Context, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use handlebars::Handlebars;
+use pulldown_cmark::{html, Options, Parser as MarkdownParser};
 use toml::{Table, Value};
 use walkdir::WalkDir;
 
+mod bib;
+mod cache;
+mod epub;
+mod highlight;
+mod scss;
+mod search;
+mod serve;
+
+use cache::Manifest;
+use highlight::Highlighter;
+use search::SearchDoc;
+
 /// Custom static site generator.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
-struct Args {
+pub(crate) struct Args {
     /// Paths to Handlebars template files or directories containing template files
     #[arg(short, long, default_values_os_t = [PathBuf::from("templates")])]
-    templates: Vec<PathBuf>,
+    pub(crate) templates: Vec<PathBuf>,
     /// Path to the output directory
     #[arg(short, long, default_value_os_t = PathBuf::from("docs"))]
-    out_dir: PathBuf,
+    pub(crate) out_dir: PathBuf,
     /// Path to the input directory
     #[arg(short, long, default_value_os_t = PathBuf::from("src"))]
-    in_dir: PathBuf,
+    pub(crate) in_dir: PathBuf,
+    /// Also assemble the rendered site into a single EPUB at this path
+    #[arg(long)]
+    pub(crate) epub: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Build once, then serve `out_dir` and rebuild on changes to `in_dir`/`templates`
+    Serve {
+        /// Port to serve the site on
+        #[arg(short, long, default_value_t = 8000)]
+        port: u16,
+    },
+}
+
+/// Everything a render pass needs besides the `Handlebars` registry and the
+/// file currently being processed.
+pub(crate) struct BuildOptions<'a> {
+    pub(crate) highlighter: &'a Highlighter,
+    pub(crate) templates: Vec<PathBuf>,
+    pub(crate) manifest: Manifest,
+    pub(crate) live_reload: bool,
+    pub(crate) search_docs: Vec<SearchDoc>,
+    pub(crate) epub_chapters: Vec<epub::Chapter>,
+    /// Bibliographies keyed by their resolved input path, reparsed whenever
+    /// the file's own hash changes and reused otherwise by every page that
+    /// cites it.
+    pub(crate) bibliographies: std::collections::HashMap<PathBuf, (String, bib::Bibliography)>,
 }
 
 #[derive(Clone, Copy, Debug)]
-struct FileHandle<'a> {
-    file: &'a Path,
-    in_dir: &'a Path,
-    out_dir: &'a Path,
+pub(crate) struct FileHandle<'a> {
+    pub(crate) file: &'a Path,
+    pub(crate) in_dir: &'a Path,
+    pub(crate) out_dir: &'a Path,
 }
 
 impl<'a> FileHandle<'a> {
@@ -62,15 +105,42 @@ fn main() -> Result<()> {
     let args = Args::parse();
 
     let mut handlebars = Handlebars::new();
+    register_templates(&mut handlebars, &args.templates)?;
+
+    let highlighter = Highlighter::new();
+    let manifest = Manifest::load(&args.out_dir);
 
-    for template in args.templates {
+    let live_reload = matches!(args.command, Some(Command::Serve { .. }));
+    let mut opts = BuildOptions {
+        highlighter: &highlighter,
+        templates: args.templates.clone(),
+        manifest,
+        live_reload,
+        search_docs: Vec::new(),
+        epub_chapters: Vec::new(),
+        bibliographies: std::collections::HashMap::new(),
+    };
+    build(&mut handlebars, &mut opts, &args)?;
+
+    if let Some(epub_path) = &args.epub {
+        epub::export(epub_path, &args.in_dir, &args.out_dir, &opts.epub_chapters)
+            .context("Failed to export the EPUB")?;
+    }
+
+    match args.command {
+        None => Ok(()),
+        Some(Command::Serve { port }) => serve::run(handlebars, opts, args, port),
+    }
+}
+
+pub(crate) fn register_templates(hb: &mut Handlebars, templates: &[PathBuf]) -> Result<()> {
+    for template in templates {
         if template.is_file() {
             let name = match template.file_stem().and_then(|name| name.to_str()) {
                 Some(name) => name,
                 _ => continue,
             };
-            handlebars
-                .register_template_file(name, &template)
+            hb.register_template_file(name, template)
                 .with_context(|| {
                     format!(
                         "Failed to register the template file at {}",
@@ -78,8 +148,7 @@ fn main() -> Result<()> {
                     )
                 })?;
         } else if template.is_dir() {
-            handlebars
-                .register_templates_directory(&template, Default::default())
+            hb.register_templates_directory(template, Default::default())
                 .with_context(|| {
                     format!(
                         "Failed to register the template files in {}",
@@ -89,25 +158,65 @@ fn main() -> Result<()> {
         }
     }
 
+    Ok(())
+}
+
+/// Render `args.in_dir` into `args.out_dir` and copy over the default static
+/// assets. Used both for one-shot builds and for the initial render before
+/// `serve` starts watching.
+pub(crate) fn build(hb: &mut Handlebars, opts: &mut BuildOptions, args: &Args) -> Result<()> {
     let root = FileHandle {
         file: Path::new(""),
         in_dir: &args.in_dir,
         out_dir: &args.out_dir,
     };
 
-    render_dir(&mut handlebars, root)?;
+    opts.search_docs.clear();
+    opts.epub_chapters.clear();
+    render_dir(hb, opts, root)?;
+    search::write_index(&args.out_dir, &opts.search_docs)?;
 
-    let default_style = FileHandle {
-        file: &root.file.join("style.css"),
-        ..root
-    };
+    process_default_stylesheet(args)?;
+
+    fs::write(args.out_dir.join("highlight.css"), highlight::default_css())
+        .context("Failed to write default syntax highlighting stylesheet")?;
 
-    default_style.copy()?;
+    fs::write(args.out_dir.join("search.js"), include_str!("search.js"))
+        .context("Failed to write search.js")?;
+
+    opts.manifest.prune_missing(&args.in_dir, &args.out_dir, true);
+    opts.manifest
+        .save(&args.out_dir)
+        .context("Failed to save the build manifest")?;
 
     Ok(())
 }
 
-fn render_dir(hb: &mut Handlebars, dir: FileHandle) -> Result<()> {
+/// The site-wide stylesheet path relative to `in_dir`: `style.scss` if
+/// present, otherwise `style.css`.
+pub(crate) fn default_stylesheet_path(args: &Args) -> PathBuf {
+    let scss_stylesheet = PathBuf::from("style.scss");
+    if args.in_dir.join(&scss_stylesheet).is_file() {
+        scss_stylesheet
+    } else {
+        PathBuf::from("style.css")
+    }
+}
+
+/// Compile/copy the site-wide stylesheet into `out_dir`. Called both from a
+/// full `build()` and, during `serve`, whenever the stylesheet itself
+/// changes without any page or template also changing.
+pub(crate) fn process_default_stylesheet(args: &Args) -> Result<()> {
+    let stylesheet = default_stylesheet_path(args);
+    scss::process(FileHandle {
+        file: &stylesheet,
+        in_dir: &args.in_dir,
+        out_dir: &args.out_dir,
+    })?;
+    Ok(())
+}
+
+pub(crate) fn render_dir(hb: &mut Handlebars, opts: &mut BuildOptions, dir: FileHandle) -> Result<()> {
     if !dir.in_dir.is_dir() {
         bail!(
             "Input path should be a directory, {} is not a directory",
@@ -127,7 +236,7 @@ fn render_dir(hb: &mut Handlebars, dir: FileHandle) -> Result<()> {
         if entry.file_type().is_dir() {
             fs::create_dir_all(file.out_file())?;
         } else if entry.file_type().is_file() {
-            if let Err(_) = render_file(hb, file) {
+            if let Err(_) = render_file(hb, opts, file) {
                 continue;
             }
         }
@@ -136,72 +245,245 @@ fn render_dir(hb: &mut Handlebars, dir: FileHandle) -> Result<()> {
     Ok(())
 }
 
-fn render_file(hb: &mut Handlebars, file: FileHandle) -> Result<()> {
-    let mut data = read_source(file.in_file()).context("Failed to read source file")?;
-
-    parse_body(&mut data)?;
+pub(crate) fn render_file(hb: &mut Handlebars, opts: &mut BuildOptions, file: FileHandle) -> Result<()> {
+    let source_bytes = fs::read(file.in_file()).context("Failed to read source file")?;
+    let mut data = read_source_bytes(&source_bytes, file.in_file())?;
 
     let template = data["template"]
         .as_str()
-        .context("'template' field should be a String")?;
-    let rendered = hb
-        .render(template, &data)
-        .with_context(|| format!("Failed to render template {template} with data {data:#?}"))?;
+        .context("'template' field should be a String")?
+        .to_string();
+    let template_path = cache::resolve_template_path(&template, &opts.templates);
+    let template_bytes = template_path
+        .as_deref()
+        .map(fs::read)
+        .transpose()
+        .context("Failed to read the resolved template file")?
+        .unwrap_or_default();
 
-    fs::write(file.out_file(), rendered).context("Failed to write rendered output to file")?;
+    // Fold in the bytes of every referenced stylesheet/script/asset/
+    // bibliography too, so editing one of them (without touching the source
+    // or template) still invalidates this page's manifest entry.
+    let referenced_bytes: Vec<Vec<u8>> = referenced_asset_paths(&data)
+        .into_iter()
+        .map(|path| {
+            let handle = FileHandle { file: &path, ..file };
+            fs::read(handle.in_file()).unwrap_or_default()
+        })
+        .collect();
 
-    if let Some(stylesheet) = data.get("stylesheet").and_then(|v| v.as_str()) {
-        let stylesheet = FileHandle {
-            file: Path::new(stylesheet),
-            ..file
-        };
-        stylesheet.copy()?;
-    }
+    // The source and output paths are the same relative path, mirrored
+    // under `in_dir` and `out_dir` respectively.
+    let relative = file.file.to_string_lossy().into_owned();
+    let template_hash = cache::hash_inputs(&[&template_bytes]);
+    let mut combined_inputs: Vec<&[u8]> = vec![&source_bytes, &template_bytes];
+    combined_inputs.extend(referenced_bytes.iter().map(|bytes| bytes.as_slice()));
+    // Live-reload output carries `RELOAD_SNIPPET`, so it must not satisfy a
+    // one-shot build's manifest entry (or the other way round).
+    let mode: &[u8] = if opts.live_reload { b"live-reload" } else { b"" };
+    combined_inputs.push(mode);
+    let combined_hash = cache::hash_inputs(&combined_inputs);
 
-    if let Some(script) = data.get("script").and_then(|v| v.as_str()) {
-        let script = FileHandle {
-            file: Path::new(script),
-            ..file
-        };
-        script.copy()?;
-    }
+    opts.manifest.record_template(&template, template_hash);
 
-    match data.get("assets") {
-        None => {}
-        Some(Value::String(asset)) => {
-            let asset = FileHandle {
-                file: Path::new(asset),
+    let is_stale = opts.manifest.is_stale(&relative, &file.out_file(), &combined_hash);
+
+    // Resolved up front (cheap, no I/O) so pages that are skipped this build
+    // still report the right stylesheet path to the EPUB exporter below.
+    let stylesheet_path = data
+        .get("stylesheet")
+        .and_then(|v| v.as_str())
+        .map(|stylesheet| scss::resolved_extension(Path::new(stylesheet)));
+
+    if is_stale {
+        parse_body(&mut data)?;
+
+        if let Some(body) = data.get("BODY").and_then(|v| v.as_str()) {
+            let highlighted = opts.highlighter.highlight_code(body);
+            data.insert("BODY".to_string(), highlighted.into());
+        }
+
+        if let Some(bibliography) = data.get("bibliography").and_then(|v| v.as_str()) {
+            let handle = FileHandle {
+                file: Path::new(bibliography),
+                ..file
+            };
+            let bib_path = handle.in_file();
+            let bib_hash = cache::hash_inputs(&[&fs::read(&bib_path).unwrap_or_default()]);
+            let needs_reload = opts
+                .bibliographies
+                .get(&bib_path)
+                .map_or(true, |(hash, _)| *hash != bib_hash);
+            if needs_reload {
+                let loaded = bib::Bibliography::load(&bib_path)?;
+                opts.bibliographies.insert(bib_path.clone(), (bib_hash, loaded));
+            }
+            let bibliography = &opts.bibliographies[&bib_path].1;
+
+            if let Some(body) = data.get("BODY").and_then(|v| v.as_str()) {
+                let cited = bib::render_citations(body, bibliography);
+                data.insert("BODY".to_string(), cited.into());
+            }
+            data.insert("bibliography_entries".to_string(), bibliography.to_template_value());
+        }
+
+        if let Some(stylesheet) = data.get("stylesheet").and_then(|v| v.as_str()) {
+            let handle = FileHandle {
+                file: Path::new(stylesheet),
                 ..file
             };
-            asset.copy()?;
+            scss::process(handle)?;
         }
-        Some(Value::Array(assets)) => {
-            for asset in assets.into_iter().filter_map(|v| v.as_str()) {
+
+        if let Some(resolved) = &stylesheet_path {
+            data.insert(
+                "stylesheet".to_string(),
+                resolved.to_string_lossy().into_owned().into(),
+            );
+        }
+
+        let mut rendered = hb
+            .render(&template, &data)
+            .with_context(|| format!("Failed to render template {template} with data {data:#?}"))?;
+
+        if opts.live_reload {
+            rendered.push_str(serve::RELOAD_SNIPPET);
+        }
+
+        fs::write(file.out_file(), rendered).context("Failed to write rendered output to file")?;
+
+        if let Some(script) = data.get("script").and_then(|v| v.as_str()) {
+            let script = FileHandle {
+                file: Path::new(script),
+                ..file
+            };
+            script.copy()?;
+        }
+
+        match data.get("assets") {
+            None => {}
+            Some(Value::String(asset)) => {
                 let asset = FileHandle {
                     file: Path::new(asset),
                     ..file
                 };
                 asset.copy()?;
             }
+            Some(Value::Array(assets)) => {
+                for asset in assets.into_iter().filter_map(|v| v.as_str()) {
+                    let asset = FileHandle {
+                        file: Path::new(asset),
+                        ..file
+                    };
+                    asset.copy()?;
+                }
+            }
+            _ => bail!("the 'assets' field should be a single file or an array of file"),
         }
-        _ => bail!("the 'assets' field should be a single file or an array of file"),
+
+        opts.manifest.record_page(relative.clone(), relative.clone(), combined_hash);
     }
 
+    let title = data
+        .get("title")
+        .and_then(|v| v.as_str())
+        .unwrap_or(&relative)
+        .to_string();
+    let rendered_output =
+        fs::read_to_string(file.out_file()).context("Failed to read rendered output for indexing")?;
+
+    let order = data
+        .get("order")
+        .or_else(|| data.get("nav"))
+        .and_then(|v| v.as_integer());
+    let assets = match data.get("assets") {
+        Some(Value::String(asset)) => vec![PathBuf::from(asset)],
+        Some(Value::Array(assets)) => assets
+            .iter()
+            .filter_map(|v| v.as_str())
+            .map(PathBuf::from)
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    epub::upsert(
+        &mut opts.epub_chapters,
+        epub::Chapter {
+            order,
+            title: title.clone(),
+            url: relative.clone(),
+            html: rendered_output.clone(),
+            stylesheet: stylesheet_path,
+            assets,
+        },
+    );
+
+    search::upsert(
+        &mut opts.search_docs,
+        SearchDoc {
+            title,
+            url: relative,
+            text: search::strip_tags(&rendered_output),
+        },
+    );
+
     Ok(())
 }
 
+/// Every file referenced by a page's front matter (`stylesheet`, `script`,
+/// `bibliography`, `assets`), relative to `in_dir`, so their
+/// bytes can be folded into its manifest hash.
+fn referenced_asset_paths(data: &Table) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+
+    for field in ["stylesheet", "script", "bibliography"] {
+        if let Some(path) = data.get(field).and_then(|v| v.as_str()) {
+            paths.push(PathBuf::from(path));
+        }
+    }
+
+    match data.get("assets") {
+        Some(Value::String(asset)) => paths.push(PathBuf::from(asset)),
+        Some(Value::Array(assets)) => {
+            paths.extend(assets.iter().filter_map(|v| v.as_str()).map(PathBuf::from));
+        }
+        _ => {}
+    }
+
+    paths
+}
+
 fn parse_body(data: &mut Table) -> Result<()> {
-    match data["type"] {
-        Value::String(ref typ) if typ == "html" => Ok(()),
-        Value::String(ref typ) => Err(anyhow!("Cannot handle files of type {typ} yet")),
-        ref x => Err(anyhow!(
-            "Expected the 'type' field to be a String. Instead it was {x:?}"
-        )),
+    let typ = match &data["type"] {
+        Value::String(typ) => typ.clone(),
+        x => bail!("Expected the 'type' field to be a String. Instead it was {x:?}"),
+    };
+
+    match typ.as_str() {
+        "html" => Ok(()),
+        "md" | "markdown" => {
+            let body = data["BODY"]
+                .as_str()
+                .context("'BODY' field should be a String")?;
+
+            let mut options = Options::empty();
+            options.insert(Options::ENABLE_TABLES);
+            options.insert(Options::ENABLE_FOOTNOTES);
+            options.insert(Options::ENABLE_STRIKETHROUGH);
+            let parser = MarkdownParser::new_ext(body, options);
+
+            let mut html = String::new();
+            html::push_html(&mut html, parser);
+
+            data.insert("BODY".to_string(), html.into());
+            Ok(())
+        }
+        _ => Err(anyhow!("Cannot handle files of type {typ} yet")),
     }
 }
 
-fn read_source(source: impl AsRef<Path>) -> Result<Table> {
-    let content = fs::read_to_string(source.as_ref())?;
+fn read_source_bytes(content: &[u8], source: impl AsRef<Path>) -> Result<Table> {
+    let content = std::str::from_utf8(content).context("Source file is not valid UTF-8")?;
     let (meta, body) = content
         .split_once("*** ssg ***\n")
         .context("Not a source file")?;
@@ -222,3 +504,39 @@ fn read_source(source: impl AsRef<Path>) -> Result<Table> {
 
     Ok(data)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args_with_in_dir(in_dir: PathBuf) -> Args {
+        Args {
+            templates: Vec::new(),
+            out_dir: PathBuf::new(),
+            in_dir,
+            epub: None,
+            command: None,
+        }
+    }
+
+    #[test]
+    fn default_stylesheet_path_prefers_scss_when_present() {
+        let dir = std::env::temp_dir().join(format!("ssg-main-test-scss-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("style.scss"), "").unwrap();
+
+        assert_eq!(default_stylesheet_path(&args_with_in_dir(dir.clone())), PathBuf::from("style.scss"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn default_stylesheet_path_falls_back_to_css() {
+        let dir = std::env::temp_dir().join(format!("ssg-main-test-css-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        assert_eq!(default_stylesheet_path(&args_with_in_dir(dir.clone())), PathBuf::from("style.css"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,68 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::FileHandle;
+
+fn is_sass(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("scss") | Some("sass")
+    )
+}
+
+/// The path a `.scss`/`.sass` stylesheet will be written under once
+/// compiled: same path, `.css` extension. Paths already ending in `.css`
+/// are returned unchanged.
+pub(crate) fn resolved_extension(path: &Path) -> PathBuf {
+    if is_sass(path) {
+        path.with_extension("css")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Compile a `.scss`/`.sass` stylesheet to CSS and write it to the mirrored
+/// output path (with a `.css` extension), or copy it through untouched if
+/// it's already CSS. Returns the path that should replace the `stylesheet`
+/// field so templates emit a `<link>` pointing at whatever actually landed
+/// in `out_dir`.
+pub(crate) fn process(stylesheet: FileHandle) -> Result<PathBuf> {
+    if !is_sass(stylesheet.file) {
+        stylesheet.copy()?;
+        return Ok(stylesheet.file.to_path_buf());
+    }
+
+    let css = grass::from_path(stylesheet.in_file(), &grass::Options::default()).with_context(|| {
+        format!("Failed to compile {}", stylesheet.in_file().display())
+    })?;
+
+    let out_path = resolved_extension(stylesheet.file);
+    let out_file = stylesheet.out_dir.join(&out_path);
+    if let Some(parent) = out_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&out_file, css)
+        .with_context(|| format!("Failed to write compiled stylesheet to {}", out_file.display()))?;
+
+    Ok(out_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolved_extension_swaps_scss_and_sass_to_css() {
+        assert_eq!(resolved_extension(Path::new("style.scss")), PathBuf::from("style.css"));
+        assert_eq!(resolved_extension(Path::new("app.sass")), PathBuf::from("app.css"));
+    }
+
+    #[test]
+    fn resolved_extension_leaves_css_unchanged() {
+        assert_eq!(resolved_extension(Path::new("style.css")), PathBuf::from("style.css"));
+    }
+}
@@ -0,0 +1,88 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Serialize;
+
+/// Plain-text record of a single rendered page, collected while walking
+/// `in_dir` so an inverted index can be built once the walk is done.
+#[derive(Debug, Clone)]
+pub(crate) struct SearchDoc {
+    pub(crate) title: String,
+    pub(crate) url: String,
+    pub(crate) text: String,
+}
+
+#[derive(Serialize)]
+struct DocMeta {
+    title: String,
+    url: String,
+}
+
+#[derive(Serialize)]
+struct SearchIndex {
+    documents: Vec<DocMeta>,
+    postings: BTreeMap<String, Vec<usize>>,
+}
+
+/// Record (or replace, by URL) a page's text for the next index write.
+pub(crate) fn upsert(docs: &mut Vec<SearchDoc>, doc: SearchDoc) {
+    docs.retain(|existing| existing.url != doc.url);
+    docs.push(doc);
+}
+
+/// Strip markup down to plain text, the same way for every page, so
+/// indexing and querying tokenize identically.
+pub(crate) fn strip_tags(html: &str) -> String {
+    let tag = Regex::new(r"(?s)<[^>]*>").expect("static regex should compile");
+    tag.replace_all(html, " ").into_owned()
+}
+
+/// Split on non-alphanumerics, lowercase, and drop tokens shorter than 2
+/// characters. `search.js` mirrors this exactly.
+pub(crate) fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(|token| token.to_lowercase())
+        .filter(|token| token.chars().count() >= 2)
+        .collect()
+}
+
+/// Build the inverted index from every collected page and write it to
+/// `out_dir/search-index.json`.
+pub(crate) fn write_index(out_dir: &Path, docs: &[SearchDoc]) -> Result<()> {
+    let mut postings: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    let mut documents = Vec::with_capacity(docs.len());
+
+    for (id, doc) in docs.iter().enumerate() {
+        documents.push(DocMeta {
+            title: doc.title.clone(),
+            url: doc.url.clone(),
+        });
+
+        let mut seen = std::collections::HashSet::new();
+        for token in tokenize(&doc.text) {
+            if seen.insert(token.clone()) {
+                postings.entry(token).or_default().push(id);
+            }
+        }
+    }
+
+    let index = SearchIndex { documents, postings };
+    let json = serde_json::to_string(&index).context("Failed to serialize the search index")?;
+    fs::write(out_dir.join("search-index.json"), json).context("Failed to write search-index.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_lowercases_and_splits_on_punctuation() {
+        assert_eq!(tokenize("Hello, World!"), vec!["hello", "world"]);
+    }
+
+    #[test]
+    fn tokenize_drops_single_character_tokens() {
+        assert_eq!(tokenize("a bb c"), vec!["bb"]);
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{RecvTimeoutError, TryRecvError},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use handlebars::Handlebars;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use tiny_http::{Header, Response, Server};
+
+use crate::{
+    build, process_default_stylesheet, register_templates, render_file, Args, BuildOptions,
+    FileHandle,
+};
+
+/// Appended to every page rendered while `serve` is running. Polls a small
+/// endpoint for the current build generation and reloads the page once it
+/// changes, so authors don't have to refresh by hand.
+pub(crate) const RELOAD_SNIPPET: &str = r#"
+<script>
+(function () {
+    var lastSeen = null;
+    function poll() {
+        fetch("/__reload")
+            .then(function (res) { return res.text(); })
+            .then(function (generation) {
+                if (lastSeen !== null && generation !== lastSeen) {
+                    location.reload();
+                }
+                lastSeen = generation;
+                setTimeout(poll, 500);
+            })
+            .catch(function () { setTimeout(poll, 1000); });
+    }
+    poll();
+})();
+</script>
+"#;
+
+/// Serve `args.out_dir` over HTTP, re-rendering whenever `args.in_dir` or a
+/// template changes and nudging connected browsers to reload.
+pub(crate) fn run(mut hb: Handlebars, mut opts: BuildOptions, args: Args, port: u16) -> Result<()> {
+    let generation = Arc::new(AtomicU64::new(0));
+
+    let (watch_tx, watch_rx) = std::sync::mpsc::channel();
+    let mut watcher: RecommendedWatcher =
+        notify::recommended_watcher(watch_tx).context("Failed to start the filesystem watcher")?;
+    watcher
+        .watch(&args.in_dir, RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {}", args.in_dir.display()))?;
+    for template in &args.templates {
+        watcher
+            .watch(template, RecursiveMode::Recursive)
+            .with_context(|| format!("Failed to watch {}", template.display()))?;
+    }
+
+    let server = Server::http(("127.0.0.1", port))
+        .map_err(|err| anyhow::anyhow!("Failed to bind the dev server to port {port}: {err}"))?;
+    println!(
+        "Serving {} on http://127.0.0.1:{port} (watching {})",
+        args.out_dir.display(),
+        args.in_dir.display()
+    );
+
+    {
+        let generation = Arc::clone(&generation);
+        let out_dir = args.out_dir.clone();
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                if request.url() == "/__reload" {
+                    let body = generation.load(Ordering::SeqCst).to_string();
+                    let _ = request.respond(Response::from_string(body));
+                } else {
+                    respond_with_file(request, &out_dir);
+                }
+            }
+        });
+    }
+
+    loop {
+        match watch_rx.recv_timeout(Duration::from_millis(200)) {
+            Ok(Ok(event)) => {
+                if event.paths.iter().any(|path| is_template_path(path, &args.templates)) {
+                    register_templates(&mut hb, &args.templates)
+                        .context("Failed to re-register templates")?;
+                    build(&mut hb, &mut opts, &args)?;
+                } else if event
+                    .paths
+                    .iter()
+                    .any(|path| is_default_stylesheet_path(path, &args.in_dir))
+                {
+                    let _ = process_default_stylesheet(&args);
+                } else {
+                    for path in &event.paths {
+                        if let Ok(relative) = path.strip_prefix(&args.in_dir) {
+                            let file = FileHandle {
+                                file: relative,
+                                in_dir: &args.in_dir,
+                                out_dir: &args.out_dir,
+                            };
+                            let _ = render_file(&mut hb, &mut opts, file);
+                        }
+                    }
+                    let _ = crate::search::write_index(&args.out_dir, &opts.search_docs);
+                }
+                generation.fetch_add(1, Ordering::SeqCst);
+                drain_pending(&watch_rx);
+            }
+            Ok(Err(_)) | Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Coalesce a burst of filesystem events (editors often emit several per
+/// save) into the single rebuild above.
+fn drain_pending(rx: &std::sync::mpsc::Receiver<notify::Result<notify::Event>>) {
+    loop {
+        match rx.try_recv() {
+            Ok(_) => continue,
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => break,
+        }
+    }
+}
+
+fn is_template_path(path: &Path, templates: &[std::path::PathBuf]) -> bool {
+    templates.iter().any(|template| path.starts_with(template))
+}
+
+/// Whether `path` is the site-wide stylesheet (by either candidate name, not
+/// just whichever currently exists), which `render_file` can't handle on its
+/// own since it isn't a page source with front matter. Matching both names
+/// rather than re-deriving `default_stylesheet_path` avoids missing a rename
+/// between the two (e.g. `style.scss` replaced by `style.css`).
+fn is_default_stylesheet_path(path: &Path, in_dir: &Path) -> bool {
+    path.strip_prefix(in_dir)
+        .map(|relative| matches!(relative.to_str(), Some("style.scss") | Some("style.css")))
+        .unwrap_or(false)
+}
+
+fn respond_with_file(request: tiny_http::Request, out_dir: &Path) {
+    let mut requested = request.url().trim_start_matches('/').to_string();
+    if requested.is_empty() || requested.ends_with('/') {
+        requested.push_str("index.html");
+    }
+
+    let result = resolve_within(out_dir, Path::new(&requested)).and_then(|path| {
+        let mut file = fs::File::open(&path)?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        Ok((path, contents))
+    });
+
+    match result {
+        Ok((path, contents)) => {
+            let header = content_type(&path);
+            let response = Response::from_data(contents).with_header(header);
+            let _ = request.respond(response);
+        }
+        Err(_) => {
+            let _ = request.respond(Response::from_string("404 Not Found").with_status_code(404));
+        }
+    }
+}
+
+/// Join `requested` onto `out_dir` and reject it unless the resolved path is
+/// still inside `out_dir`, so a request like `/../../../etc/passwd` can't
+/// read files outside the served directory.
+fn resolve_within(out_dir: &Path, requested: &Path) -> io::Result<PathBuf> {
+    let out_dir = out_dir.canonicalize()?;
+    let resolved = out_dir.join(requested).canonicalize()?;
+    if resolved.starts_with(&out_dir) {
+        Ok(resolved)
+    } else {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "path escapes out_dir"))
+    }
+}
+
+fn content_type(path: &Path) -> Header {
+    let mime = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        _ => "application/octet-stream",
+    };
+    Header::from_bytes(&b"Content-Type"[..], mime.as_bytes()).expect("static header is valid")
+}